@@ -0,0 +1,192 @@
+//! ANSIコードページ(SJISなど)とワイド文字列(UTF-16)の相互変換、
+//! および`Charset:`ヘッダーで宣言される任意の文字コード間の変換を行うモジュール。
+
+use winapi::_core::ptr::null_mut;
+use winapi::shared::minwindef::UINT;
+use winapi::um::stringapiset::{MultiByteToWideChar, WideCharToMultiByte};
+use winapi::um::winnls::{CP_ACP, MB_ERR_INVALID_CHARS};
+
+/// WinAPIの文字コード変換に失敗したことを表すエラー。
+#[derive(Copy, Eq, PartialEq, Clone, Debug)]
+pub struct EncodingError;
+
+/// 文字コードの種類。
+#[derive(Copy, Eq, PartialEq, Clone, Debug)]
+pub enum Encoding {
+    /// システムのANSIコードページ(JP環境ではSJIS)。
+    ANSI,
+    /// 任意のWindowsコードページ番号(`CP932`など)。
+    CodePage(UINT),
+}
+
+impl Encoding {
+    fn codepage(&self) -> UINT {
+        match self {
+            Encoding::ANSI => CP_ACP,
+            Encoding::CodePage(cp) => *cp,
+        }
+    }
+
+    /// このコードページのbytesをワイド文字列(UTF-16)に変換します。
+    /// 不正なバイト列は既定の置換文字になります。
+    pub fn to_wide(&self, bytes: &[u8]) -> Vec<u16> {
+        self.to_wide_impl(bytes, 0).unwrap_or_else(Vec::new)
+    }
+
+    /// このコードページのbytesをワイド文字列(UTF-16)に変換します。
+    /// 不正なバイト列が含まれる場合はエラーを返します。
+    pub fn to_wide_strict(&self, bytes: &[u8]) -> Result<Vec<u16>, EncodingError> {
+        self.to_wide_impl(bytes, MB_ERR_INVALID_CHARS)
+            .ok_or(EncodingError)
+    }
+
+    fn to_wide_impl(&self, bytes: &[u8], flags: UINT) -> Option<Vec<u16>> {
+        if bytes.is_empty() {
+            return Some(Vec::new());
+        }
+        unsafe {
+            let cp = self.codepage();
+            let src = bytes.as_ptr() as *const i8;
+            let src_len = bytes.len() as i32;
+            let len = MultiByteToWideChar(cp, flags, src, src_len, null_mut(), 0);
+            if len <= 0 {
+                return None;
+            }
+            let mut wide = vec![0u16; len as usize];
+            let written = MultiByteToWideChar(cp, flags, src, src_len, wide.as_mut_ptr(), len);
+            if written <= 0 {
+                return None;
+            }
+            Some(wide)
+        }
+    }
+
+    /// ワイド文字列(UTF-16)をこのコードページのbytesに変換します。
+    pub fn from_wide(&self, wide: &[u16]) -> Result<Vec<u8>, EncodingError> {
+        if wide.is_empty() {
+            return Ok(Vec::new());
+        }
+        unsafe {
+            let cp = self.codepage();
+            let len = WideCharToMultiByte(
+                cp,
+                0,
+                wide.as_ptr(),
+                wide.len() as i32,
+                null_mut(),
+                0,
+                null_mut(),
+                null_mut(),
+            );
+            if len <= 0 {
+                return Err(EncodingError);
+            }
+            let mut bytes = vec![0u8; len as usize];
+            let written = WideCharToMultiByte(
+                cp,
+                0,
+                wide.as_ptr(),
+                wide.len() as i32,
+                bytes.as_mut_ptr() as *mut i8,
+                len,
+                null_mut(),
+                null_mut(),
+            );
+            if written <= 0 {
+                return Err(EncodingError);
+            }
+            Ok(bytes)
+        }
+    }
+
+    /// bytesをこのコードページの文字列とみなし、Stringに変換します。
+    /// 不正なバイト列は既定の置換文字になります(厳格に検出したい場合は
+    /// `to_wide_strict`を使う`GStr::to_ansi_str_strict`を利用してください)。
+    pub fn to_string(&self, bytes: &[u8]) -> Result<String, EncodingError> {
+        let wide = self.to_wide(bytes);
+        String::from_utf16(&wide).map_err(|_| EncodingError)
+    }
+
+    /// 文字列をこのコードページのbytesに変換します。
+    pub fn to_bytes(&self, text: &str) -> Result<Vec<u8>, EncodingError> {
+        let wide: Vec<u16> = text.encode_utf16().collect();
+        self.from_wide(&wide)
+    }
+}
+
+/// SHIORIの`Charset:`ヘッダーで宣言される文字コード名をbytesからUTF-8の
+/// Stringに変換します(glibの`g_convert`相当)。
+///
+/// `encoding_rs`が認識するラベル(`UTF-8`/`Shift_JIS`/`EUC-JP`/`ISO-2022-JP`/
+/// `UTF-16`など)はそちらで復号し、認識できない名前は`CPnnn`や`nnn`のような
+/// Windowsコードページ番号とみなして`MultiByteToWideChar`にフォールバックします。
+pub fn decode_with_charset(bytes: &[u8], charset: &str) -> Result<String, EncodingError> {
+    if let Some(encoding) = encoding_rs::Encoding::for_label(charset.as_bytes()) {
+        let (text, _, had_errors) = encoding.decode(bytes);
+        return if had_errors {
+            Err(EncodingError)
+        } else {
+            Ok(text.into_owned())
+        };
+    }
+    let cp = codepage_from_name(charset).ok_or(EncodingError)?;
+    let wide = Encoding::CodePage(cp).to_wide_strict(bytes)?;
+    String::from_utf16(&wide).map_err(|_| EncodingError)
+}
+
+/// StringをSHIORIの`Charset:`ヘッダーで宣言される文字コード名のbytesに
+/// 変換します(`decode_with_charset`の逆方向)。
+///
+/// `encoding_rs`が認識するラベルはそちらでエンコードし、認識できない名前は
+/// `CPnnn`や`nnn`のようなWindowsコードページ番号とみなして
+/// `WideCharToMultiByte`にフォールバックします。
+///
+/// `UTF-16`系のラベルは`encode_utf16_with_charset`で先に処理します。
+/// WHATWG Encoding Standardでは出力エンコーディングにUTF-16LE/BEを
+/// 指定してもUTF-8に差し替えられる仕様になっており、`encoding_rs`の
+/// `Encoding::encode`もそれに従うため、そのまま使うと`Charset: UTF-16`と
+/// 宣言したのに実際の本文がUTF-8のbytesになってしまいます。
+pub fn encode_with_charset(text: &str, charset: &str) -> Result<Vec<u8>, EncodingError> {
+    if let Some(bytes) = encode_utf16_with_charset(text, charset) {
+        return Ok(bytes);
+    }
+    if let Some(encoding) = encoding_rs::Encoding::for_label(charset.as_bytes()) {
+        let (bytes, actual_encoding, had_errors) = encoding.encode(text);
+        return if had_errors || actual_encoding != encoding {
+            Err(EncodingError)
+        } else {
+            Ok(bytes.into_owned())
+        };
+    }
+    let cp = codepage_from_name(charset).ok_or(EncodingError)?;
+    Encoding::CodePage(cp).to_bytes(text)
+}
+
+/// `UTF-16`/`UTF-16LE`/`UTF-16BE`への変換を手動で行います。
+/// `UTF-16`(エンディアン無指定)はリトルエンディアンのBOM付きで書き出します
+/// (Windows環境での一般的な既定値に合わせています)。該当しないラベルは
+/// `None`を返し、呼び出し側で`encoding_rs`側の処理にフォールバックします。
+fn encode_utf16_with_charset(text: &str, charset: &str) -> Option<Vec<u8>> {
+    let units: Vec<u16> = text.encode_utf16().collect();
+    if charset.eq_ignore_ascii_case("UTF-16") {
+        let mut bytes = Vec::with_capacity(2 + units.len() * 2);
+        bytes.extend_from_slice(&0xFEFFu16.to_le_bytes());
+        bytes.extend(units.iter().flat_map(|unit| unit.to_le_bytes().to_vec()));
+        Some(bytes)
+    } else if charset.eq_ignore_ascii_case("UTF-16LE") {
+        Some(units.iter().flat_map(|unit| unit.to_le_bytes().to_vec()).collect())
+    } else if charset.eq_ignore_ascii_case("UTF-16BE") {
+        Some(units.iter().flat_map(|unit| unit.to_be_bytes().to_vec()).collect())
+    } else {
+        None
+    }
+}
+
+/// `CP932`や`932`のようなWindowsコードページ名を数値に変換します。
+fn codepage_from_name(charset: &str) -> Option<UINT> {
+    let digits = charset
+        .strip_prefix("CP")
+        .or_else(|| charset.strip_prefix("cp"))
+        .unwrap_or(charset);
+    digits.parse().ok()
+}
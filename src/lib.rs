@@ -1,10 +1,16 @@
-#![cfg(any(windows))]
+#![cfg(windows)]
 
 mod enc;
+mod response;
+pub mod saori;
+pub mod shiori;
 mod windows;
 
-use crate::enc::{Encoder, Encoding};
+use crate::enc::Encoding;
+use std::borrow::Cow;
 use std::ffi::OsString;
+use std::ops::Deref;
+use std::os::windows::ffi::OsStringExt;
 use std::str;
 use winapi::_core::mem::transmute;
 use winapi::_core::slice::{from_raw_parts, from_raw_parts_mut};
@@ -19,6 +25,13 @@ const GMEM_FIXED: UINT = 0;
 pub enum GStrError {
     AnsiEncode,
     Utf8Error(Utf8Error),
+    /// `Charset:`ヘッダーで指定された文字コードが未知、または復号に失敗した。
+    CharsetError,
+    /// `NulCheck`で末尾のnulバイトを要求したが、見つからなかった。
+    MissingNul,
+    /// `NulCheck::Forbidden`でバッファ中に、または`NulCheck::Trailing`で
+    /// 末尾以外の位置にnulバイトが見つかった。
+    UnexpectedNul,
 }
 impl From<Utf8Error> for GStrError {
     fn from(err: Utf8Error) -> GStrError {
@@ -26,16 +39,108 @@ impl From<Utf8Error> for GStrError {
     }
 }
 
+/// [`GStrRef::from_utf8_with_nul`]が行うnulバイト検証の種類。
+#[derive(Copy, Eq, PartialEq, Clone, Debug)]
+pub enum NulCheck {
+    /// nulバイトを検証しない。
+    None,
+    /// 末尾にちょうど1つのnulバイトがあることを要求し、strからは取り除く。
+    Trailing,
+    /// バッファ中にnulバイトが一切無いことを要求する。
+    Forbidden,
+}
+
+/// HGLOBALの内容を所有権なしに覗き見る、借用ビュー。
+/// `GStr::as_gstr_ref`で既存のGStrから無償で得られるほか、
+/// 任意の`&[u8]`からも作成できます。
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug)]
+pub struct GStrRef<'a>(&'a [u8]);
+
+impl<'a> GStrRef<'a> {
+    /// &[u8]をそのままGStrRefにします。
+    pub fn from_bytes(bytes: &'a [u8]) -> GStrRef<'a> {
+        GStrRef(bytes)
+    }
+
+    /// バッファがUTF-8として妥当であることを検証してGStrRefを作成します。
+    /// `nul`に`Trailing`/`Forbidden`を指定すると、あわせてnulバイトの
+    /// 有無も検証します(`Trailing`の場合、末尾のnulは取り除かれます)。
+    pub fn from_utf8_with_nul(bytes: &'a [u8], nul: NulCheck) -> Result<GStrRef<'a>, GStrError> {
+        let body = match nul {
+            NulCheck::None => bytes,
+            NulCheck::Trailing => match bytes.split_last() {
+                Some((0, rest)) => {
+                    if rest.contains(&0) {
+                        return Err(GStrError::UnexpectedNul);
+                    }
+                    rest
+                }
+                _ => return Err(GStrError::MissingNul),
+            },
+            NulCheck::Forbidden => {
+                if bytes.contains(&0) {
+                    return Err(GStrError::UnexpectedNul);
+                }
+                bytes
+            }
+        };
+        str::from_utf8(body)?;
+        Ok(GStrRef(body))
+    }
+
+    /// UTF-8として解釈したstrを取得します。
+    /// 呼び出し前に`from_utf8_with_nul`などでUTF-8として妥当であることを
+    /// 確認しておいてください。
+    pub fn to_utf8_str(&self) -> Result<&'a str, GStrError> {
+        Ok(str::from_utf8(self.0)?)
+    }
+}
+
+impl<'a> Deref for GStrRef<'a> {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        self.0
+    }
+}
+
+impl<'a> AsRef<[u8]> for GStrRef<'a> {
+    fn as_ref(&self) -> &[u8] {
+        self.0
+    }
+}
+
+impl<'a> PartialEq for GStrRef<'a> {
+    fn eq(&self, other: &GStrRef<'a>) -> bool {
+        self.0 == other.0
+    }
+}
+impl<'a> Eq for GStrRef<'a> {}
+
+/// GStrが保持するHGLOBALの所有権。
+#[derive(Copy, Eq, PartialEq, Clone, Debug)]
+pub enum GStrOwnership {
+    /// 借用のみ。drop時にHGLOBALを開放しない。
+    /// 呼び出し元が引き続き所有するHGLOBALを覗き見る場合に使う
+    /// (SAORIのrequestハンドルなど、自分が開放してはいけないもの)。
+    Borrowed,
+    /// drop時にHGLOBALを開放する。
+    FreeOnDrop,
+    /// baseware側に開放を委ねる。drop時にHGLOBALを開放しない。
+    /// 応答として`request()`の戻り値にするGStrに使う。
+    HandOff,
+}
+
 /// HGLOBALを文字列にキャプチャーします。
 pub struct GStr {
     h: HGLOBAL,
     len: usize,
-    has_free: bool,
+    ownership: GStrOwnership,
 }
 
 impl Drop for GStr {
     fn drop(&mut self) {
-        if !self.has_free {
+        if self.ownership != GStrOwnership::FreeOnDrop {
             return;
         }
         unsafe {
@@ -52,12 +157,24 @@ impl GStr {
         GStr {
             h: h,
             len: len,
-            has_free: true,
+            ownership: GStrOwnership::FreeOnDrop,
+        }
+    }
+
+    /// HGLOBALを開放せずにGStrから覗き見ます。
+    /// drop時にHGLOBALを開放しません。
+    /// SAORI::request/load/unloadが受け取るHGLOBALのように、
+    /// 呼び出し元が引き続き所有し続けるハンドルの参照に利用してください。
+    pub fn capture_borrowed(h: HGLOBAL, len: usize) -> GStr {
+        GStr {
+            h: h,
+            len: len,
+            ownership: GStrOwnership::Borrowed,
         }
     }
 
     /// &[u8]をHGLOBAL領域にコピーして返す。
-    fn clone_from_slice_impl(bytes: &[u8], has_free: bool) -> GStr {
+    fn clone_from_slice_impl(bytes: &[u8], ownership: GStrOwnership) -> GStr {
         let len = bytes.len();
         unsafe {
             let h = GlobalAlloc(GMEM_FIXED, len as size_t);
@@ -67,16 +184,16 @@ impl GStr {
             GStr {
                 h: h,
                 len: len,
-                has_free: has_free,
+                ownership: ownership,
             }
         }
     }
 
     /// HGLOBALを新たに作成し、&[u8]をGStrにクローンします。
-    /// drop時にHGLOBALを開放しません。
-    /// shiori応答の作成に利用してください。
+    /// drop時にHGLOBALを開放せず、所有権をbaseware側に委ねます(HandOff)。
+    /// shiori/saori応答の作成に利用してください。
     pub fn clone_from_slice_nofree(bytes: &[u8]) -> GStr {
-        GStr::clone_from_slice_impl(bytes, false)
+        GStr::clone_from_slice_impl(bytes, GStrOwnership::HandOff)
     }
 
     /// 要素を&[u8]として参照します。
@@ -97,6 +214,12 @@ impl GStr {
         self.len
     }
 
+    /// 所有権を持たない借用ビューを無償で取得します。
+    /// `handle()`/`len()`を手動で持ち回る代わりに利用してください。
+    pub fn as_gstr_ref(&self) -> GStrRef {
+        GStrRef(self.to_bytes())
+    }
+
     /// 格納データを「ANSI STRING(JP環境ではSJIS)」とみなして、OsStrに変換します。
     /// MultiByteToWideChar()を利用する。
     /// SHIORI::load()文字列の取り出しに利用する。
@@ -115,7 +238,74 @@ impl GStr {
         let bytes = self.to_bytes();
         Ok(str::from_utf8(bytes)?)
     }
+
+    /// 格納データを「ANSI STRING(JP環境ではSJIS)」とみなして、ワイド文字列(UTF-16)に変換します。
+    /// MultiByteToWideChar()を利用する。不正なバイト列は既定の置換文字になります。
+    pub fn to_wide(&self) -> Vec<u16> {
+        Encoding::ANSI.to_wide(self.to_bytes())
+    }
+
+    /// ワイド文字列(UTF-16)を「ANSI STRING(JP環境ではSJIS)」のbytesに変換し、GStrを作成します。
+    /// WideCharToMultiByte()を利用する。drop時にHGLOBALを開放しません。
+    /// shiori応答の作成に利用してください。
+    pub fn from_wide(wide: &[u16]) -> Result<GStr, GStrError> {
+        let bytes = Encoding::ANSI
+            .from_wide(wide)
+            .map_err(|_| GStrError::AnsiEncode)?;
+        Ok(GStr::clone_from_slice_nofree(&bytes))
+    }
+
+    /// 格納データを「ANSI STRING(JP環境ではSJIS)」とみなして、OsStringに欠落なく変換します。
+    /// to_wide()で得たワイド文字列をOsStringExt::from_wide()でそのまま包むため、
+    /// Stringを経由する変換と違い不対サロゲートや非SJIS文字も失われません。
+    /// SHIORI::load()文字列の取り出しに利用する。
+    pub fn to_os_string_lossless(&self) -> OsString {
+        OsString::from_wide(&self.to_wide())
+    }
+
+    /// 格納データを「ANSI STRING(JP環境ではSJIS)」とみなして、OsStringに変換します(厳格モード)。
+    /// MultiByteToWideChar()にMB_ERR_INVALID_CHARSを指定するため、
+    /// 変換できないバイト列が含まれる場合は置換せずGStrErrorを返します。
+    pub fn to_ansi_str_strict(&self) -> Result<OsString, GStrError> {
+        let wide = Encoding::ANSI
+            .to_wide_strict(self.to_bytes())
+            .map_err(|_| GStrError::AnsiEncode)?;
+        Ok(OsString::from_wide(&wide))
+    }
+
+    /// 格納データを、SHIORIリクエストの`Charset:`ヘッダーで宣言された文字コード
+    /// (`UTF-8`/`Shift_JIS`/`EUC-JP`/`ISO-2022-JP`/`UTF-16`や任意のコードページ番号)
+    /// とみなしてstrに変換します。`UTF-8`の場合はコピーを行いません。
+    pub fn to_str_with_charset(&self, charset: &str) -> Result<Cow<str>, GStrError> {
+        let bytes = self.to_bytes();
+        if charset.eq_ignore_ascii_case("UTF-8") {
+            return Ok(Cow::Borrowed(str::from_utf8(bytes)?));
+        }
+        let text =
+            enc::decode_with_charset(bytes, charset).map_err(|_| GStrError::CharsetError)?;
+        Ok(Cow::Owned(text))
+    }
+}
+
+impl Deref for GStr {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        self.to_bytes()
+    }
+}
+
+impl AsRef<[u8]> for GStr {
+    fn as_ref(&self) -> &[u8] {
+        self.to_bytes()
+    }
+}
+
+impl PartialEq for GStr {
+    fn eq(&self, other: &GStr) -> bool {
+        self.to_bytes() == other.to_bytes()
+    }
 }
+impl Eq for GStr {}
 
 #[test]
 fn gstr_test() {
@@ -144,3 +334,83 @@ fn gstr_test() {
         assert_eq!(src_str, text);
     }
 }
+
+#[test]
+fn gstr_capture_borrowed_test() {
+    let text = "適当なGSTR";
+    let src = GStr::clone_from_slice_nofree(text.as_bytes());
+
+    let view = GStr::capture_borrowed(src.handle(), src.len());
+    assert_eq!(view.to_utf8_str().unwrap(), text);
+    drop(view);
+
+    // 借用はHGLOBALを開放しないので、元のGStrから引き続き読み出せる。
+    assert_eq!(src.to_utf8_str().unwrap(), text);
+}
+
+#[test]
+fn gstr_wide_test() {
+    let text = "適当なGSTR";
+    let sjis = Encoding::ANSI.to_bytes(text).unwrap();
+    let src = GStr::clone_from_slice_nofree(&sjis[..]);
+
+    let wide = src.to_wide();
+    assert_eq!(wide, text.encode_utf16().collect::<Vec<u16>>());
+
+    let dst = GStr::from_wide(&wide).unwrap();
+    assert_eq!(dst.to_bytes(), src.to_bytes());
+
+    assert_eq!(src.to_os_string_lossless(), src.to_ansi_str().unwrap());
+    assert_eq!(src.to_ansi_str_strict().unwrap(), src.to_ansi_str().unwrap());
+}
+
+#[test]
+fn gstr_ansi_malformed_test() {
+    // 0x81は2バイトSJIS文字の先頭バイトだが、後続バイトが無く不正な列になる。
+    let malformed = [0x81u8];
+    let src = GStr::clone_from_slice_nofree(&malformed[..]);
+
+    // 既定(非strict)は置換文字で処理を継続する。
+    assert!(src.to_ansi_str().is_ok());
+
+    // strictは不正なバイト列を検出してエラーを返す。
+    assert!(src.to_ansi_str_strict().is_err());
+}
+
+#[test]
+fn gstr_charset_test() {
+    let text = "適当なGSTR";
+
+    let utf8 = GStr::clone_from_slice_nofree(text.as_bytes());
+    assert_eq!(utf8.to_str_with_charset("UTF-8").unwrap(), text);
+
+    let sjis_bytes = Encoding::ANSI.to_bytes(text).unwrap();
+    let sjis = GStr::clone_from_slice_nofree(&sjis_bytes[..]);
+    assert_eq!(sjis.to_str_with_charset("Shift_JIS").unwrap(), text);
+
+    assert_eq!(
+        sjis.to_str_with_charset("CP932").unwrap(),
+        sjis.to_str_with_charset("Shift_JIS").unwrap()
+    );
+}
+
+#[test]
+fn gstr_ref_test() {
+    let text = "適当なGSTR";
+    let src = GStr::clone_from_slice_nofree(text.as_bytes());
+
+    let view = src.as_gstr_ref();
+    assert_eq!(&*view, text.as_bytes());
+    assert_eq!(view.to_utf8_str().unwrap(), text);
+    assert_eq!(view, GStrRef::from_bytes(text.as_bytes()));
+
+    assert_eq!(&*src, text.as_bytes());
+    assert_eq!(src, GStr::clone_from_slice_nofree(text.as_bytes()));
+
+    let mut with_nul = text.as_bytes().to_vec();
+    with_nul.push(0);
+    let nul_view = GStrRef::from_utf8_with_nul(&with_nul, NulCheck::Trailing).unwrap();
+    assert_eq!(nul_view.to_utf8_str().unwrap(), text);
+    assert!(GStrRef::from_utf8_with_nul(text.as_bytes(), NulCheck::Trailing).is_err());
+    assert!(GStrRef::from_utf8_with_nul(&with_nul, NulCheck::Forbidden).is_err());
+}
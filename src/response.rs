@@ -0,0 +1,69 @@
+//! SHIORI/SAORIに共通する`プロトコル名 状態行\r\nName: Value\r\n...\r\n`形式の
+//! レスポンスを組み立てる内部ヘルパー。
+//!
+//! SHIORI/SAORIのレスポンスビルダーはどちらも同じワイヤーフォーマットを
+//! 持つため、共通の組み立て処理をここに集約し、`shiori`/`saori`モジュールの
+//! 公開ビルダーはプロトコル名と固有の便利メソッドだけを持つ薄いラッパーにする。
+
+use crate::enc;
+use crate::{GStr, GStrError};
+
+/// SHIORI/SAORIに共通するレスポンスビルダー。
+pub(crate) struct ResponseBuilder {
+    version: &'static str,
+    status: &'static str,
+    charset: Option<Box<str>>,
+    headers: Vec<(Box<str>, Box<str>)>,
+}
+
+impl ResponseBuilder {
+    /// プロトコル名(`SHIORI/3.0`/`SAORI/1.0`)と状態行からビルダーを作成します。
+    pub(crate) fn new(version: &'static str, status: &'static str) -> ResponseBuilder {
+        ResponseBuilder {
+            version,
+            status,
+            charset: None,
+            headers: Vec::new(),
+        }
+    }
+
+    /// ヘッダーを追加します。
+    pub(crate) fn header(mut self, name: &str, value: &str) -> ResponseBuilder {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// `Charset`ヘッダーを追加します。`build()`時、他のヘッダー値はここで
+    /// 指定した文字コードのbytesにエンコードしてから書き出されます。
+    pub(crate) fn charset(mut self, charset: &str) -> ResponseBuilder {
+        self.charset = Some(charset.into());
+        self.header("Charset", charset)
+    }
+
+    /// 組み立てた応答をバイト列にシリアライズし、`request()`からそのまま
+    /// 返せるGStrにします。drop時にHGLOBALを開放しません。
+    /// `charset()`でUTF-8以外を指定していた場合、その文字コードへの
+    /// エンコードに失敗すると`GStrError`を返します。
+    pub(crate) fn build(self) -> Result<GStr, GStrError> {
+        let mut text = String::new();
+        text.push_str(self.version);
+        text.push(' ');
+        text.push_str(self.status);
+        text.push_str("\r\n");
+        for (name, value) in &self.headers {
+            text.push_str(name);
+            text.push_str(": ");
+            text.push_str(value);
+            text.push_str("\r\n");
+        }
+        text.push_str("\r\n");
+
+        let bytes = match &self.charset {
+            Some(charset) if !charset.eq_ignore_ascii_case("UTF-8") => {
+                enc::encode_with_charset(&text, charset).map_err(|_| GStrError::CharsetError)?
+            }
+            _ => text.into_bytes(),
+        };
+        Ok(GStr::clone_from_slice_nofree(&bytes))
+    }
+}
@@ -0,0 +1,77 @@
+//! SAORIモジュール向けのレスポンス組み立てヘルパー。
+//!
+//! SAORIの`request`/`load`/`unload`は、受け取ったリクエストHGLOBALを
+//! 開放してはならず、代わりに自分で確保した応答HGLOBALの所有権をbaseware側に
+//! 渡すという、SHIORIとは鏡写しの所有権規約を持ちます。受け取ったHGLOBALは
+//! [`crate::GStr::capture_borrowed`]で覗き見て、[`ResponseBuilder`]で組み立てた
+//! 応答を`request()`の戻り値にしてください。
+
+use crate::{GStr, GStrError};
+
+/// SAORIレスポンスを組み立てるビルダー。
+pub struct ResponseBuilder(crate::response::ResponseBuilder);
+
+impl ResponseBuilder {
+    /// `SAORI/1.0 200 OK`の応答を組み立てます。
+    pub fn ok() -> ResponseBuilder {
+        ResponseBuilder(crate::response::ResponseBuilder::new("SAORI/1.0", "200 OK"))
+    }
+
+    /// `SAORI/1.0 204 No Content`の応答を組み立てます。
+    pub fn no_content() -> ResponseBuilder {
+        ResponseBuilder(crate::response::ResponseBuilder::new(
+            "SAORI/1.0",
+            "204 No Content",
+        ))
+    }
+
+    /// ヘッダーを追加します。
+    pub fn header(self, name: &str, value: &str) -> ResponseBuilder {
+        ResponseBuilder(self.0.header(name, value))
+    }
+
+    /// `Charset`ヘッダーを追加します。`build()`時、他のヘッダー値はここで
+    /// 指定した文字コードのbytesにエンコードしてから書き出されます。
+    pub fn charset(self, charset: &str) -> ResponseBuilder {
+        ResponseBuilder(self.0.charset(charset))
+    }
+
+    /// `Result`ヘッダーを追加します。
+    pub fn result(self, value: &str) -> ResponseBuilder {
+        self.header("Result", value)
+    }
+
+    /// 組み立てた応答をSAORI/1.0形式のバイト列にシリアライズし、
+    /// `request()`からそのまま返せるGStrにします。drop時にHGLOBALを開放しません。
+    /// `charset()`でUTF-8以外を指定していた場合、その文字コードへの
+    /// エンコードに失敗すると`GStrError`を返します。
+    pub fn build(self) -> Result<GStr, GStrError> {
+        self.0.build()
+    }
+}
+
+#[test]
+fn saori_response_test() {
+    let res = ResponseBuilder::ok()
+        .charset("UTF-8")
+        .result("1")
+        .build()
+        .unwrap();
+    assert_eq!(
+        res.to_utf8_str().unwrap(),
+        "SAORI/1.0 200 OK\r\nCharset: UTF-8\r\nResult: 1\r\n\r\n"
+    );
+}
+
+#[test]
+fn saori_response_charset_test() {
+    let res = ResponseBuilder::ok()
+        .charset("Shift_JIS")
+        .result("テスト")
+        .build()
+        .unwrap();
+    assert_eq!(
+        res.to_str_with_charset("Shift_JIS").unwrap(),
+        "SAORI/1.0 200 OK\r\nCharset: Shift_JIS\r\nResult: テスト\r\n\r\n"
+    );
+}
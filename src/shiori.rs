@@ -0,0 +1,289 @@
+//! SHIORI/3.0のリクエスト/レスポンス解析を行うモジュール。
+//!
+//! `GStr::to_bytes()`で取り出したバイト列を構造化された[`Request`]に変換し、
+//! [`ResponseBuilder`]で組み立てたレスポンスを`request()`からそのまま返せる
+//! `GStr`にシリアライズします。解析はバイト単位の走査のみで行うため、
+//! `Charset`ヘッダーを読む前にバッファ全体をUTF-8として検証する必要はありません。
+
+use crate::{GStr, GStrError};
+use std::borrow::Cow;
+use std::str;
+
+/// SHIORIリクエストの解析に失敗したことを表すエラー。
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub enum ShioriError {
+    /// リクエスト行が見つからない、または不正。
+    InvalidRequestLine,
+    /// ヘッダー行が不正。
+    InvalidHeader,
+}
+
+/// SHIORIリクエストメソッド。
+#[derive(Copy, Eq, PartialEq, Clone, Debug)]
+pub enum Method {
+    GET,
+    NOTIFY,
+}
+
+/// 解析済みのSHIORIリクエスト。
+/// `GET SHIORI/3.0\r\n`に続く`Name: Value\r\n`のヘッダー行を、
+/// 末尾の空行まで読み取ったものです。
+/// ヘッダー名はUTF-8であることを検証しますが、値は宣言された`Charset`で
+/// しか意味のあるバイト列になりうるため、生のbytesのまま保持します。
+#[derive(Clone, Debug)]
+pub struct Request {
+    method: Method,
+    version: Box<str>,
+    headers: Vec<(Box<str>, Box<[u8]>)>,
+}
+
+impl Request {
+    /// バイト列をSHIORI/3.0リクエストとして解析します。
+    pub fn parse(bytes: &[u8]) -> Result<Request, ShioriError> {
+        let mut lines = split_lines(bytes);
+
+        let request_line = lines.next().ok_or(ShioriError::InvalidRequestLine)?;
+        let (method, version) = parse_request_line(request_line)?;
+
+        let mut headers = Vec::new();
+        for line in lines {
+            if line.is_empty() {
+                break;
+            }
+            headers.push(parse_header(line)?);
+        }
+
+        Ok(Request {
+            method,
+            version,
+            headers,
+        })
+    }
+
+    /// リクエストメソッド(`GET`/`NOTIFY`)を取得します。
+    pub fn method(&self) -> Method {
+        self.method
+    }
+
+    /// `SHIORI/3.0`などのバージョン文字列を取得します。
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    /// 解析済みのヘッダー一覧を、生のbytesのまま取得します。
+    pub fn headers(&self) -> &[(Box<str>, Box<[u8]>)] {
+        &self.headers
+    }
+
+    /// `ID`ヘッダーの値を取得します。
+    pub fn id(&self) -> Option<&str> {
+        self.header("ID")
+    }
+
+    /// `Charset`ヘッダーの値を取得します。未指定の場合は`"Shift_JIS"`とみなします。
+    /// 文字コード名自体は常にASCIIなので、UTF-8として解釈して問題ありません。
+    pub fn charset(&self) -> &str {
+        self.header("Charset").unwrap_or("Shift_JIS")
+    }
+
+    /// `n`番目の`ReferenceN`ヘッダーの値を、このリクエストの宣言済み
+    /// (または既定の)文字コードでデコードして取得します。
+    pub fn reference(&self, n: usize) -> Option<Result<Cow<str>, ShioriError>> {
+        self.header_with_charset(&format!("Reference{}", n))
+    }
+
+    /// 指定した名前のヘッダー値を、bytesのまま取得します
+    /// (大文字小文字を区別しません)。
+    pub fn header_bytes(&self, name: &str) -> Option<&[u8]> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_ref())
+    }
+
+    /// 指定した名前のヘッダー値をUTF-8として取得します
+    /// (大文字小文字を区別しません)。`ID`や`Charset`のようにASCIIのみを
+    /// 想定するヘッダー向けで、`Charset`で宣言された文字コードのバイト列には
+    /// `header_with_charset`を使ってください。
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.header_bytes(name).and_then(|bytes| str::from_utf8(bytes).ok())
+    }
+
+    /// 指定した名前のヘッダー値を、このリクエストの宣言済み(または既定の)
+    /// 文字コードでデコードして取得します。
+    pub fn header_with_charset(&self, name: &str) -> Option<Result<Cow<str>, ShioriError>> {
+        let bytes = self.header_bytes(name)?;
+        let charset = self.charset();
+        if charset.eq_ignore_ascii_case("UTF-8") {
+            return Some(
+                str::from_utf8(bytes)
+                    .map(Cow::Borrowed)
+                    .map_err(|_| ShioriError::InvalidHeader),
+            );
+        }
+        Some(
+            crate::enc::decode_with_charset(bytes, charset)
+                .map(Cow::Owned)
+                .map_err(|_| ShioriError::InvalidHeader),
+        )
+    }
+}
+
+/// `\r\n`または`\n`区切りで行に分割します(`bstr`のような非UTF-8前提の走査)。
+fn split_lines(bytes: &[u8]) -> impl Iterator<Item = &[u8]> {
+    bytes.split(|&b| b == b'\n').map(|line| match line {
+        [rest @ .., b'\r'] => rest,
+        line => line,
+    })
+}
+
+fn parse_request_line(line: &[u8]) -> Result<(Method, Box<str>), ShioriError> {
+    let text = str::from_utf8(line).map_err(|_| ShioriError::InvalidRequestLine)?;
+    let mut parts = text.splitn(2, ' ');
+    let method = match parts.next() {
+        Some("GET") => Method::GET,
+        Some("NOTIFY") => Method::NOTIFY,
+        _ => return Err(ShioriError::InvalidRequestLine),
+    };
+    let version = parts.next().ok_or(ShioriError::InvalidRequestLine)?;
+    Ok((method, version.into()))
+}
+
+/// ヘッダー行を`Name`と生のbytesの`Value`に分割します。
+/// ヘッダー名はASCIIの識別子であることを前提にUTF-8として検証しますが、
+/// 値は`Charset`ヘッダーが宣言する文字コードでのみ意味を持つため、
+/// ここではUTF-8として検証しません。
+fn parse_header(line: &[u8]) -> Result<(Box<str>, Box<[u8]>), ShioriError> {
+    let pos = line
+        .iter()
+        .position(|&b| b == b':')
+        .ok_or(ShioriError::InvalidHeader)?;
+    let name = str::from_utf8(&line[..pos]).map_err(|_| ShioriError::InvalidHeader)?;
+    let value = trim_ascii_whitespace(&line[pos + 1..]);
+    Ok((name.trim().into(), value.into()))
+}
+
+/// 先頭と末尾のASCII空白(スペース/タブ)のみを取り除きます。
+/// `str::trim`と違い非UTF-8のバイト列にも使えます。
+fn trim_ascii_whitespace(bytes: &[u8]) -> &[u8] {
+    fn is_space(b: &u8) -> bool {
+        *b == b' ' || *b == b'\t'
+    }
+    let start = bytes.iter().position(|b| !is_space(b)).unwrap_or(bytes.len());
+    let end = bytes.iter().rposition(|b| !is_space(b)).map_or(start, |i| i + 1);
+    &bytes[start..end]
+}
+
+/// SHIORIレスポンスを組み立てるビルダー。
+pub struct ResponseBuilder(crate::response::ResponseBuilder);
+
+impl ResponseBuilder {
+    /// `SHIORI/3.0 200 OK`の応答を組み立てます。
+    pub fn ok() -> ResponseBuilder {
+        ResponseBuilder(crate::response::ResponseBuilder::new("SHIORI/3.0", "200 OK"))
+    }
+
+    /// `SHIORI/3.0 204 No Content`の応答を組み立てます。
+    pub fn no_content() -> ResponseBuilder {
+        ResponseBuilder(crate::response::ResponseBuilder::new(
+            "SHIORI/3.0",
+            "204 No Content",
+        ))
+    }
+
+    /// ヘッダーを追加します。
+    pub fn header(self, name: &str, value: &str) -> ResponseBuilder {
+        ResponseBuilder(self.0.header(name, value))
+    }
+
+    /// `Charset`ヘッダーを追加します。`build()`時、他のヘッダー値はここで
+    /// 指定した文字コードのbytesにエンコードしてから書き出されます。
+    pub fn charset(self, charset: &str) -> ResponseBuilder {
+        ResponseBuilder(self.0.charset(charset))
+    }
+
+    /// `Value`ヘッダーを追加します。
+    pub fn value(self, value: &str) -> ResponseBuilder {
+        self.header("Value", value)
+    }
+
+    /// 組み立てた応答をSHIORI/3.0形式のバイト列にシリアライズし、
+    /// `request()`からそのまま返せるGStrにします。drop時にHGLOBALを開放しません。
+    /// `charset()`でUTF-8以外を指定していた場合、その文字コードへの
+    /// エンコードに失敗すると`GStrError`を返します。
+    pub fn build(self) -> Result<GStr, GStrError> {
+        self.0.build()
+    }
+}
+
+#[test]
+fn shiori_request_test() {
+    let raw = b"GET SHIORI/3.0\r\nID: OnBoot\r\nReference0: 0\r\nCharset: UTF-8\r\n\r\n";
+    let req = Request::parse(raw).unwrap();
+    assert_eq!(req.method(), Method::GET);
+    assert_eq!(req.version(), "SHIORI/3.0");
+    assert_eq!(req.id(), Some("OnBoot"));
+    assert_eq!(req.reference(0).unwrap().unwrap(), "0");
+    assert_eq!(req.charset(), "UTF-8");
+}
+
+#[test]
+fn shiori_request_shift_jis_header_test() {
+    // Charsetヘッダーが無いリクエストは既定のShift_JISとみなし、
+    // 非UTF-8の日本語バイト列を含むヘッダーでも解析自体は失敗しない。
+    let sjis = crate::enc::Encoding::ANSI.to_bytes("テスト").unwrap();
+    let mut raw = b"NOTIFY SHIORI/3.0\r\nID: OnBoot\r\nReference0: ".to_vec();
+    raw.extend_from_slice(&sjis);
+    raw.extend_from_slice(b"\r\n\r\n");
+
+    let req = Request::parse(&raw).unwrap();
+    assert_eq!(req.method(), Method::NOTIFY);
+    assert_eq!(req.charset(), "Shift_JIS");
+    assert_eq!(req.header_bytes("Reference0").unwrap(), &sjis[..]);
+    // ヘッダー値は非UTF-8なので、UTF-8前提のheader()では取得できない。
+    assert_eq!(req.header("Reference0"), None);
+    // 宣言された(既定の)文字コードでデコードすれば正しく読める。
+    assert_eq!(req.reference(0).unwrap().unwrap(), "テスト");
+}
+
+#[test]
+fn shiori_response_test() {
+    let res = ResponseBuilder::ok()
+        .charset("UTF-8")
+        .value("\\h\\s0テスト")
+        .build()
+        .unwrap();
+    assert_eq!(
+        res.to_utf8_str().unwrap(),
+        "SHIORI/3.0 200 OK\r\nCharset: UTF-8\r\nValue: \\h\\s0テスト\r\n\r\n"
+    );
+}
+
+#[test]
+fn shiori_response_charset_test() {
+    let res = ResponseBuilder::ok()
+        .charset("Shift_JIS")
+        .value("テスト")
+        .build()
+        .unwrap();
+    assert_eq!(
+        res.to_str_with_charset("Shift_JIS").unwrap(),
+        "SHIORI/3.0 200 OK\r\nCharset: Shift_JIS\r\nValue: テスト\r\n\r\n"
+    );
+}
+
+#[test]
+fn shiori_response_utf16_test() {
+    // `encoding_rs`はデフォルトではUTF-16への出力をUTF-8に差し替えてしまうため、
+    // `Charset: UTF-16`で宣言した本文が実際にUTF-16のbytesになっていることを確認する。
+    let res = ResponseBuilder::ok()
+        .charset("UTF-16")
+        .value("テスト")
+        .build()
+        .unwrap();
+    assert_eq!(
+        res.to_str_with_charset("UTF-16").unwrap(),
+        "SHIORI/3.0 200 OK\r\nCharset: UTF-16\r\nValue: テスト\r\n\r\n"
+    );
+    assert!(res.to_utf8_str().is_err());
+}
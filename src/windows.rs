@@ -0,0 +1 @@
+//! Windows API呼び出しに関する低レベルのヘルパーを置くモジュール。